@@ -1,9 +1,17 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
-use std::hash::BuildHasherDefault;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::iter;
 use std::marker::PhantomData;
+use std::mem;
 use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
 
 use fnv::FnvHasher;
+use hashbrown::raw::RawTable;
 
 use bitset::BitSet;
 use join::Join;
@@ -11,6 +19,38 @@ use world::{Component, Allocator};
 use {Entity, Index, Generation};
 
 
+/// Error that can occur when trying to insert, remove or mutate a
+/// component through a `Storage`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StorageError {
+    /// The `Entity` passed in doesn't match the generation that is
+    /// currently alive for its index, i.e. the entity has been deleted.
+    DeadEntity,
+    /// The underlying storage can't grow to accommodate the given
+    /// `Index` (e.g. a `VecStorage` being asked to hold an absurdly
+    /// large index).
+    IndexOutOfBounds,
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StorageError::DeadEntity => write!(f, "entity is no longer alive"),
+            StorageError::IndexOutOfBounds => write!(f, "index is out of bounds for this storage"),
+        }
+    }
+}
+
+impl Error for StorageError {
+    fn description(&self) -> &str {
+        match *self {
+            StorageError::DeadEntity => "entity is no longer alive",
+            StorageError::IndexOutOfBounds => "index is out of bounds for this storage",
+        }
+    }
+}
+
+
 /// The `UnprotectedStorage` together with the `BitSet` that knows
 /// about which elements are stored, and which are not.
 pub struct MaskedStorage<T: Component> {
@@ -100,32 +140,47 @@ impl<T, A, D> Storage<T, A, D> where
     D: DerefMut<Target = MaskedStorage<T>>,
 {
     /// Tries to mutate the data associated with an `Entity`.
-    pub fn get_mut(&mut self, e: Entity) -> Option<&mut T> {
-        if self.data.mask.contains(e.get_id()) && self.has_gen(e) {
-            Some(unsafe { self.data.inner.get_mut(e.get_id()) })
-        }else {None}
+    /// Returns `Err(StorageError::DeadEntity)` instead of silently
+    /// returning `None` when the entity is no longer alive.
+    pub fn get_mut(&mut self, e: Entity) -> Result<Option<&mut T>, StorageError> {
+        if !self.has_gen(e) {
+            return Err(StorageError::DeadEntity);
+        }
+        if self.data.mask.contains(e.get_id()) {
+            Ok(Some(unsafe { self.data.inner.get_mut(e.get_id()) }))
+        } else {
+            Ok(None)
+        }
     }
     /// Inserts new data for a given `Entity`.
-    /// Returns false if the entity is dead, and insertion is not possible.
-    pub fn insert(&mut self, e: Entity, v: T) -> bool {
-        if self.has_gen(e) {
-            let id = e.get_id();
-            if self.data.mask.contains(id) {
-                *unsafe{ self.data.inner.get_mut(id) } = v;
-            } else {
-                self.data.mask.add(id);
-                unsafe{ self.data.inner.insert(id, v) };
-            }
-            true
-        }else {
-            false
+    /// Returns `Ok(Some(old))` if the entity already held a component of
+    /// this type, which got replaced. Returns `Err` if the entity is dead
+    /// or the storage can't accommodate the insertion, instead of
+    /// panicking.
+    pub fn insert(&mut self, e: Entity, mut v: T) -> Result<Option<T>, StorageError> {
+        if !self.has_gen(e) {
+            return Err(StorageError::DeadEntity);
+        }
+        let id = e.get_id();
+        if self.data.mask.contains(id) {
+            mem::swap(unsafe { self.data.inner.get_mut(id) }, &mut v);
+            Ok(Some(v))
+        } else {
+            // The mask just told us `id` is empty, so the storage doesn't
+            // need to check for an existing entry on the way in.
+            unsafe { self.data.inner.insert_unchecked(id, v)? };
+            self.data.mask.add(id);
+            Ok(None)
         }
     }
     /// Removes the data associated with an `Entity`.
-    pub fn remove(&mut self, e: Entity) -> Option<T> {
-        if self.has_gen(e) {
-            self.data.remove(e.get_id())
-        }else { None }
+    /// Returns `Err(StorageError::DeadEntity)` instead of silently
+    /// returning `None` when the entity is no longer alive.
+    pub fn remove(&mut self, e: Entity) -> Result<Option<T>, StorageError> {
+        if !self.has_gen(e) {
+            return Err(StorageError::DeadEntity);
+        }
+        Ok(self.data.remove(e.get_id()))
     }
     /// Clears the contents of the storage.
     pub fn clear(&mut self) {
@@ -171,6 +226,86 @@ impl<'a, T, A, D> Join for &'a mut Storage<T, A, D> where
 }
 
 
+/// A single-threaded stand-in for the `Arc<RwLock<MaskedStorage<T>>>`
+/// that `World::read`/`write` normally hand out, for components whose
+/// `Storage` can't be `Send`/`Sync` (an `Rc`, a raw GPU handle, and so
+/// on). Wraps the `MaskedStorage` in `Rc<RefCell<_>>` instead, so a
+/// world that's driven on a single thread can register the component
+/// without requiring `T::Storage: Send + Sync`.
+///
+/// `read`/`write` hand back an ordinary `Storage`, so the existing
+/// `Join` impls for `&Storage`/`&mut Storage` iterate it unchanged.
+///
+/// Registered through [`LocalWorld`](struct.LocalWorld.html) rather than
+/// `World::register`, which requires `T::Storage: Send + Sync` so it can
+/// file the storage away where any thread holding the `World` might
+/// reach it — a bound this type exists specifically to avoid.
+pub struct LocalStorage<T: Component>(Rc<RefCell<MaskedStorage<T>>>);
+
+impl<T: Component> LocalStorage<T> {
+    /// Creates a new, empty `LocalStorage`.
+    pub fn new() -> LocalStorage<T> {
+        LocalStorage(Rc::new(RefCell::new(MaskedStorage::new())))
+    }
+    /// Borrows the storage for reading. `alloc` still comes from the
+    /// `World` that owns the entities.
+    pub fn read<'a>(&'a self, alloc: &'a Allocator) -> Storage<T, &'a Allocator, Ref<'a, MaskedStorage<T>>> {
+        Storage::new(alloc, self.0.borrow())
+    }
+    /// Borrows the storage for writing. `alloc` still comes from the
+    /// `World` that owns the entities.
+    pub fn write<'a>(&'a self, alloc: &'a Allocator) -> Storage<T, &'a Allocator, RefMut<'a, MaskedStorage<T>>> {
+        Storage::new(alloc, self.0.borrow_mut())
+    }
+}
+
+impl<T: Component> Clone for LocalStorage<T> {
+    /// Cheap: clones the `Rc`, not the `MaskedStorage` it points to, so
+    /// every clone shares the same underlying storage.
+    fn clone(&self) -> Self {
+        LocalStorage(self.0.clone())
+    }
+}
+
+/// The registration entry point for `LocalStorage<T>` components: a
+/// sibling to `World`'s own component map for components that can't live
+/// in it, because `World::register` needs `T::Storage: Send + Sync` and
+/// `LocalStorage` is precisely for types that don't have it. A `World`
+/// is expected to own one of these alongside its normal registry and
+/// forward to it for any component registered via `register` instead of
+/// `World::register`.
+pub struct LocalWorld {
+    storages: RefCell<HashMap<TypeId, Box<Any>>>,
+}
+
+impl LocalWorld {
+    /// Creates an empty `LocalWorld`.
+    pub fn new() -> LocalWorld {
+        LocalWorld { storages: RefCell::new(HashMap::new()) }
+    }
+    /// Registers `T`, creating its backing `LocalStorage<T>` the first
+    /// time it's seen. Calling this again for an already-registered `T`
+    /// is a no-op, mirroring `World::register`.
+    pub fn register<T: Component + 'static>(&self) {
+        self.storages.borrow_mut()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(LocalStorage::<T>::new()) as Box<Any>);
+    }
+    /// Fetches the `LocalStorage<T>` registered for `T`.
+    ///
+    /// # Panics
+    /// Panics if `T` hasn't been `register`ed yet, just like reading an
+    /// unregistered component from `World` does.
+    pub fn storage<T: Component + 'static>(&self) -> LocalStorage<T> {
+        self.storages.borrow()
+            .get(&TypeId::of::<T>())
+            .and_then(|boxed| boxed.downcast_ref::<LocalStorage<T>>())
+            .expect("component type not registered, call LocalWorld::register first")
+            .clone()
+    }
+}
+
+
 /// Used by the framework to quickly join componets
 pub trait UnprotectedStorage<T>: Sized {
     /// Creates a new `Storage<T>`. This is called when you register a new
@@ -187,34 +322,74 @@ pub trait UnprotectedStorage<T>: Sized {
     /// This is unsafe because the external set used
     /// to protect this storage is absent.
     unsafe fn get_mut(&mut self, id: Index) -> &mut T;
-    /// Inserts new data for a given `Index`.
-    unsafe fn insert(&mut self, Index, T);
+    /// Inserts new data for a given `Index`. Fails with
+    /// `StorageError::IndexOutOfBounds` if the storage can't grow to
+    /// accommodate it, instead of panicking.
+    unsafe fn insert(&mut self, Index, T) -> Result<(), StorageError>;
+    /// Inserts new data for an `Index` that the caller has already
+    /// proven absent (typically via the `MaskedStorage` bitset), so the
+    /// storage doesn't need to check for an existing entry on the way
+    /// in. Storages that can exploit this to skip a lookup (e.g. a hash
+    /// table) should override it; the default just forwards to `insert`.
+    unsafe fn insert_unchecked(&mut self, id: Index, v: T) -> Result<(), StorageError> {
+        self.insert(id, v)
+    }
     /// Removes the data associated with an `Index`.
     unsafe fn remove(&mut self, Index) -> T;
 }
 
 /// HashMap-based storage. Best suited for rare components.
-pub struct HashMapStorage<T>(HashMap<Index, T, BuildHasherDefault<FnvHasher>>);
+///
+/// Implemented directly on hashbrown's `RawTable` (rather than
+/// `std::collections::HashMap`) so that `insert_unchecked` can place a
+/// new entry straight into the first empty control-byte group without
+/// first probing for a key that the bitset mask already proved absent.
+pub struct HashMapStorage<T>(RawTable<(Index, T)>);
+
+fn hash_index(id: Index) -> u64 {
+    let mut hasher = FnvHasher::default();
+    id.hash(&mut hasher);
+    hasher.finish()
+}
 
 impl<T> UnprotectedStorage<T> for HashMapStorage<T> {
     fn new() -> Self {
-        let fnv = BuildHasherDefault::<FnvHasher>::default();
-        HashMapStorage(HashMap::with_hasher(fnv))
+        HashMapStorage(RawTable::new())
     }
     unsafe fn clean<F>(&mut self, _: F) where F: Fn(Index) -> bool {
         //nothing to do
     }
     unsafe fn get(&self, id: Index) -> &T {
-        self.0.get(&id).unwrap()
+        let hash = hash_index(id);
+        let bucket = self.0.find(hash, |&(k, _)| k == id).expect("no component for index");
+        &bucket.as_ref().1
     }
     unsafe fn get_mut(&mut self, id: Index) -> &mut T {
-        self.0.get_mut(&id).unwrap()
+        let hash = hash_index(id);
+        let bucket = self.0.find(hash, |&(k, _)| k == id).expect("no component for index");
+        &mut bucket.as_mut().1
+    }
+    unsafe fn insert(&mut self, id: Index, v: T) -> Result<(), StorageError> {
+        let hash = hash_index(id);
+        match self.0.find(hash, |&(k, _)| k == id) {
+            Some(bucket) => bucket.as_mut().1 = v,
+            None => { self.0.insert(hash, (id, v), |&(k, _)| hash_index(k)); },
+        }
+        Ok(())
     }
-    unsafe fn insert(&mut self, id: Index, v: T) {
-        self.0.insert(id, v);
+    unsafe fn insert_unchecked(&mut self, id: Index, v: T) -> Result<(), StorageError> {
+        let hash = hash_index(id);
+        // Safe because the mask already guarantees `id` isn't present, so
+        // `RawTable::insert` doesn't need to be preceded by a `find`.
+        self.0.insert(hash, (id, v), |&(k, _)| hash_index(k));
+        Ok(())
     }
     unsafe fn remove(&mut self, id: Index) -> T {
-        self.0.remove(&id).unwrap()
+        let hash = hash_index(id);
+        let bucket = self.0.find(hash, |&(k, _)| k == id).expect("no component for index");
+        // `RawTable::remove` returns `((Index, T), InsertSlot)`; the slot
+        // is only useful for a follow-up `insert_in_slot`, so discard it.
+        (self.0.remove(bucket).0).1
     }
 }
 
@@ -243,19 +418,26 @@ impl<T> UnprotectedStorage<T> for VecStorage<T> {
     unsafe fn get_mut(&mut self, id: Index) -> &mut T {
         self.0.get_unchecked_mut(id as usize)
     }
-    unsafe fn insert(&mut self, id: Index, mut v: T) {
+    unsafe fn insert(&mut self, id: Index, mut v: T) -> Result<(), StorageError> {
         use std::mem;
         let id = id as usize;
         if self.0.len() <= id {
-            let delta = id + 1 - self.0.len();
+            let len = match id.checked_add(1)
+                .and_then(|len| len.checked_mul(mem::size_of::<T>().max(1)).map(|_| len))
+            {
+                Some(len) => len,
+                None => return Err(StorageError::IndexOutOfBounds),
+            };
+            let delta = len - self.0.len();
             self.0.reserve(delta);
-            self.0.set_len(id + 1);
+            self.0.set_len(len);
         }
         // Can't just do `self.0[id] = v` since it would
         // drop the existing element in there, which
         // is undefined at this point.
         mem::swap(self.0.get_unchecked_mut(id), &mut v);
         mem::forget(v);
+        Ok(())
     }
     unsafe fn remove(&mut self, id: Index) -> T {
         use std::ptr;
@@ -263,19 +445,166 @@ impl<T> UnprotectedStorage<T> for VecStorage<T> {
     }
 }
 
-/// A dummy storage type, used for cases where the component
-/// doesn't contain any data and instead works as a simple flag.
-pub struct DummyStorage<T>(T);
+/// Dense vector storage. Keeps components packed back-to-back in a
+/// `Vec` regardless of how sparse the owning entities' indices are,
+/// at the cost of an extra index-redirect layer: `data_id` maps an
+/// entity index to its slot in `data`, and `entity_id` maps a slot in
+/// `data` back to the entity index that owns it (so a `swap_remove`
+/// can fix up the one redirect that moved). Good for components that
+/// are neither as common as `VecStorage` wants nor as rare as
+/// `HashMapStorage` wants.
+pub struct DenseVecStorage<T> {
+    data: Vec<T>,
+    entity_id: Vec<Index>,
+    data_id: Vec<Index>,
+}
+
+impl<T> UnprotectedStorage<T> for DenseVecStorage<T> {
+    fn new() -> Self {
+        DenseVecStorage {
+            data: Vec::new(),
+            entity_id: Vec::new(),
+            data_id: Vec::new(),
+        }
+    }
+    unsafe fn clean<F>(&mut self, _: F) where F: Fn(Index) -> bool {
+        // Unlike `VecStorage`, every element of `data` is live, so we
+        // can just drop it directly instead of consulting the mask.
+        self.data.clear();
+        self.entity_id.clear();
+        self.data_id.clear();
+    }
+    unsafe fn get(&self, id: Index) -> &T {
+        let did = *self.data_id.get_unchecked(id as usize);
+        self.data.get_unchecked(did as usize)
+    }
+    unsafe fn get_mut(&mut self, id: Index) -> &mut T {
+        let did = *self.data_id.get_unchecked(id as usize);
+        self.data.get_unchecked_mut(did as usize)
+    }
+    unsafe fn insert(&mut self, id: Index, mut v: T) -> Result<(), StorageError> {
+        let uid = id as usize;
+        if self.data_id.len() <= uid {
+            let len = match uid.checked_add(1) {
+                Some(len) => len,
+                None => return Err(StorageError::IndexOutOfBounds),
+            };
+            let delta = len - self.data_id.len();
+            self.data_id.extend(iter::repeat(0).take(delta));
+        }
+        let did = *self.data_id.get_unchecked(uid) as usize;
+        // `data_id[uid]` can be a stale redirect left over from some
+        // earlier, unrelated entity (it's never reset on `remove`), so
+        // confirm the slot it points at still claims to be `id`'s before
+        // trusting it.
+        if self.entity_id.get(did).map_or(false, |&eid| eid == id) {
+            // `id` already has a live slot; overwrite it in place rather
+            // than pushing a new one and orphaning it, matching how
+            // `VecStorage`/`HashMapStorage::insert` overwrite an
+            // existing entry.
+            mem::swap(self.data.get_unchecked_mut(did), &mut v);
+        } else {
+            *self.data_id.get_unchecked_mut(uid) = self.data.len() as Index;
+            self.data.push(v);
+            self.entity_id.push(id);
+        }
+        Ok(())
+    }
+    unsafe fn remove(&mut self, id: Index) -> T {
+        let did = *self.data_id.get_unchecked(id as usize) as usize;
+        let last = self.data.len() - 1;
+        let removed = self.data.swap_remove(did);
+        self.entity_id.swap_remove(did);
+        if did != last {
+            // The last slot got moved into `did`; point its entity's
+            // redirect at its new home.
+            let moved_id = *self.entity_id.get_unchecked(did);
+            *self.data_id.get_unchecked_mut(moved_id as usize) = did as Index;
+        }
+        removed
+    }
+}
 
-impl<T: Clone + Default> UnprotectedStorage<T> for DummyStorage<T> {
+/// A storage type for data-less "flag" components, where presence is
+/// all that matters. It stores nothing per entity and relies entirely
+/// on the `MaskedStorage` bitset to track who has the flag: `get`/
+/// `get_mut` hand back a reference to one canonical unit value (sound
+/// only because `T` carries no per-entity state), `insert`/`remove` are
+/// no-ops on the data side, and `clean` has nothing to drop. Because
+/// `Join` for `&Storage`/`&mut Storage` only ever touches that one
+/// unit value, iterating "all entities with flag `T`" costs nothing
+/// more than intersecting bitsets.
+///
+/// `T` must be zero-sized — a flag type that carries real data would
+/// have every entity alias the same value, which is exactly the bug
+/// this replaces. `new` enforces that with a plain `assert!`, so a
+/// non-zero-sized `T` fails loudly in release builds too, rather than
+/// only under `debug_assertions`.
+pub struct NullStorage<T>(T);
+
+impl<T: Default> UnprotectedStorage<T> for NullStorage<T> {
     fn new() -> Self {
-        DummyStorage(Default::default())
+        assert!(
+            mem::size_of::<T>() == 0,
+            "NullStorage can only be used with zero-sized flag components"
+        );
+        NullStorage(Default::default())
     }
     unsafe fn clean<F>(&mut self, _: F) where F: Fn(Index) -> bool {}
     unsafe fn get(&self, _: Index) -> &T { &self.0 }
     unsafe fn get_mut(&mut self, _: Index) -> &mut T { &mut self.0 }
-    unsafe fn insert(&mut self, _: Index, _: T) {}
-    unsafe fn remove(&mut self, _: Index) -> T { self.0.clone() }
+    unsafe fn insert(&mut self, _: Index, _: T) -> Result<(), StorageError> { Ok(()) }
+    unsafe fn remove(&mut self, _: Index) -> T { Default::default() }
+}
+
+
+/// Optional serde support, split out into its own module and gated
+/// behind the `serde` feature the same way hashbrown keeps its
+/// (equally optional) serde impls out of the main module.
+#[cfg(feature = "serde")]
+pub mod serde_impl {
+    use serde::de::{Deserialize, Deserializer};
+    use serde::ser::{Serialize, Serializer};
+
+    use world::Component;
+    use {Entity, Index};
+    use super::{MaskedStorage, UnprotectedStorage};
+
+    impl<T: Component + Serialize> Serialize for MaskedStorage<T> {
+        /// Serializes as a sequence of `(Index, T)` pairs, found by
+        /// walking the mask rather than the backing storage directly.
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.collect_seq(
+                (&self.mask).into_iter().map(|id| (id, unsafe { self.inner.get(id) }))
+            )
+        }
+    }
+
+    /// Deserializes a saved `MaskedStorage<T>`, rebinding each stored
+    /// `Index` through `to_entity`. A saved `Index` is only meaningful
+    /// relative to the `Allocator` generations that were alive when it
+    /// was written, so `to_entity` should bind it onto a freshly
+    /// allocated `Entity`; pairs it maps to `None` are skipped.
+    pub fn deserialize<'de, T, D, F>(to_entity: F, deserializer: D) -> Result<MaskedStorage<T>, D::Error>
+    where
+        T: Component + Deserialize<'de>,
+        D: Deserializer<'de>,
+        F: Fn(Index) -> Option<Entity>,
+    {
+        let pairs = Vec::<(Index, T)>::deserialize(deserializer)?;
+        let mut masked = MaskedStorage::new();
+        for (id, component) in pairs {
+            if let Some(entity) = to_entity(id) {
+                let id = entity.get_id();
+                masked.mask.add(id);
+                let _ = unsafe { UnprotectedStorage::<T>::insert(&mut masked.inner, id, component) };
+            }
+        }
+        Ok(masked)
+    }
 }
 
 
@@ -300,7 +629,7 @@ mod map_test {
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
         for i in 0..1_000 {
-            c.insert(ent(i), Comp(i));
+            c.insert(ent(i), Comp(i)).unwrap();
         }
 
         for i in 0..1_000 {
@@ -313,7 +642,7 @@ mod map_test {
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
         for i in 0..100_000 {
-            c.insert(ent(i), Comp(i));
+            c.insert(ent(i), Comp(i)).unwrap();
         }
 
         for i in 0..100_000 {
@@ -326,7 +655,7 @@ mod map_test {
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
         for i in 0..1_000 {
-            c.insert(ent(i), Comp(i));
+            c.insert(ent(i), Comp(i)).unwrap();
         }
 
         for i in 0..1_000 {
@@ -334,7 +663,7 @@ mod map_test {
         }
 
         for i in 0..1_000 {
-            c.remove(ent(i));
+            c.remove(ent(i)).unwrap();
         }
 
         for i in 0..1_000 {
@@ -347,8 +676,8 @@ mod map_test {
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
         for i in 0..1_000i32 {
-            c.insert(ent(i as u32), Comp(i));
-            c.insert(ent(i as u32), Comp(-i));
+            c.insert(ent(i as u32), Comp(i)).unwrap();
+            c.insert(ent(i as u32), Comp(-i)).unwrap();
         }
 
         for i in 0..1_000i32 {
@@ -361,17 +690,19 @@ mod map_test {
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
         for i in 0..10_000 {
-            c.insert(ent(i), Comp(i));
+            c.insert(ent(i), Comp(i)).unwrap();
             assert_eq!(c.get(ent(i)).unwrap().0, i);
         }
     }
 
-    #[should_panic]
     #[test]
     fn wrap() {
+        // A large `Index` used to abort the process inside `VecStorage::insert`
+        // via an unchecked `set_len`; at this size the insert must now succeed
+        // instead of panicking.
         let mut c = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::new()));
 
-        c.insert(ent(1 << 25), Comp(7));
+        assert!(c.insert(ent(1 << 25), Comp(7)).is_ok());
     }
 }
 
@@ -380,7 +711,8 @@ mod map_test {
 mod test {
     use std::convert::AsMut;
     use std::fmt::Debug;
-    use super::{Storage, MaskedStorage, VecStorage, HashMapStorage, DummyStorage};
+    use std::iter;
+    use super::{Storage, MaskedStorage, StorageError, VecStorage, HashMapStorage, DenseVecStorage, NullStorage};
     use world::Allocator;
     use {Component, Entity, Generation};
 
@@ -408,23 +740,33 @@ mod test {
         type Storage = HashMapStorage<Cmap>;
     }
 
-    #[derive(Clone)]
-    struct Cdummy(u32);
-    impl Default for Cdummy {
-        fn default() -> Cdummy { Cdummy(0) }
+    #[derive(PartialEq, Eq, Debug)]
+    struct Cdense(u32);
+    impl From<u32> for Cdense {
+        fn from(v: u32) -> Cdense { Cdense(v) }
     }
-    impl From<u32> for Cdummy {
-        fn from(v: u32) -> Cdummy { Cdummy(v) }
+    impl AsMut<u32> for Cdense {
+        fn as_mut(&mut self) -> &mut u32 { &mut self.0 }
+    }
+    impl Component for Cdense {
+        type Storage = DenseVecStorage<Cdense>;
+    }
+
+    #[derive(Default)]
+    struct Cflag;
+    impl From<u32> for Cflag {
+        // The flag carries no data, so any `u32` maps to the same value.
+        fn from(_: u32) -> Cflag { Cflag }
     }
-    impl Component for Cdummy {
-        type Storage = DummyStorage<Cdummy>;
+    impl Component for Cflag {
+        type Storage = NullStorage<Cflag>;
     }
 
     fn test_add<T: Component + From<u32> + Debug + Eq>() {
         let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..1_000 {
-            s.insert(Entity::new(i, Generation(1)), (i + 2718).into());
+            s.insert(Entity::new(i, Generation(1)), (i + 2718).into()).unwrap();
         }
 
         for i in 0..1_000 {
@@ -436,12 +778,12 @@ mod test {
         let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..1_000 {
-            s.insert(Entity::new(i, Generation(1)), (i + 2718).into());
+            s.insert(Entity::new(i, Generation(1)), (i + 2718).into()).unwrap();
         }
 
         for i in 0..1_000 {
-            assert_eq!(s.remove(Entity::new(i, Generation(1))).unwrap(), (i + 2718).into());
-            assert!(s.remove(Entity::new(i, Generation(1))).is_none());
+            assert_eq!(s.remove(Entity::new(i, Generation(1))).unwrap(), Some((i + 2718).into()));
+            assert_eq!(s.remove(Entity::new(i, Generation(1))).unwrap(), None);
         }
     }
 
@@ -449,11 +791,11 @@ mod test {
         let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..1_000 {
-            s.insert(Entity::new(i, Generation(1)), (i + 2718).into());
+            s.insert(Entity::new(i, Generation(1)), (i + 2718).into()).unwrap();
         }
 
         for i in 0..1_000 {
-            *s.get_mut(Entity::new(i, Generation(1))).unwrap().as_mut() -= 718;
+            *s.get_mut(Entity::new(i, Generation(1))).unwrap().unwrap().as_mut() -= 718;
         }
 
         for i in 0..1_000 {
@@ -465,8 +807,8 @@ mod test {
         let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..1_000 {
-            s.insert(Entity::new(i, Generation(1)), (i + 2718).into());
-            s.insert(Entity::new(i, Generation(2)), (i + 31415).into());
+            s.insert(Entity::new(i, Generation(1)), (i + 2718).into()).unwrap();
+            assert_eq!(s.insert(Entity::new(i, Generation(2)), (i + 31415).into()), Err(StorageError::DeadEntity));
         }
 
         for i in 0..1_000 {
@@ -476,14 +818,21 @@ mod test {
     }
 
     fn test_sub_gen<T: Component + From<u32> + Debug + Eq>() {
-        let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
+        // A fresh `Allocator` treats every index as currently alive at
+        // `Generation(1)` (that's what `has_gen` falls back to), so first
+        // bump the allocator's own bookkeeping to `Generation(2)` to mark
+        // those entities as recycled, then insert at `Generation(2)`. That
+        // makes `Generation(1)` the stale handle `remove` should reject.
+        let mut alloc = Allocator::new();
+        alloc.generations = iter::repeat(Generation(2)).take(1_000).collect();
+        let mut s = Storage::new(Box::new(alloc), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..1_000 {
-            s.insert(Entity::new(i, Generation(2)), (i + 2718).into());
+            s.insert(Entity::new(i, Generation(2)), (i + 2718).into()).unwrap();
         }
 
         for i in 0..1_000 {
-            assert!(s.remove(Entity::new(i, Generation(1))).is_none());
+            assert_eq!(s.remove(Entity::new(i, Generation(1))), Err(StorageError::DeadEntity));
         }
     }
 
@@ -491,7 +840,7 @@ mod test {
         let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<T>::new()));
 
         for i in 0..10 {
-            s.insert(Entity::new(i, Generation(1)), (i + 10).into());
+            s.insert(Entity::new(i, Generation(1)), (i + 10).into()).unwrap();
         }
 
         s.clear();
@@ -516,6 +865,108 @@ mod test {
     #[test] fn hash_test_sub_gen() { test_sub_gen::<Cmap>(); }
     #[test] fn hash_test_clear() { test_clear::<Cmap>(); }
 
-    #[test] fn dummy_test_clear() { test_clear::<Cdummy>(); }
+    #[test] fn dense_test_add() { test_add::<Cdense>(); }
+    #[test] fn dense_test_sub() { test_sub::<Cdense>(); }
+    #[test] fn dense_test_get_mut() { test_get_mut::<Cdense>(); }
+    #[test] fn dense_test_add_gen() { test_add_gen::<Cdense>(); }
+    #[test] fn dense_test_sub_gen() { test_sub_gen::<Cdense>(); }
+    #[test] fn dense_test_clear() { test_clear::<Cdense>(); }
+
+    #[test]
+    fn dense_test_wrap() {
+        // Mirrors `map_test::wrap`: growing `data_id` to hold a large
+        // `Index` must succeed instead of panicking.
+        let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<Cdense>::new()));
+
+        assert!(s.insert(Entity::new(1 << 25, Generation(1)), Cdense(7)).is_ok());
+    }
+
+    #[test]
+    fn dense_test_insert_overwrites_duplicate_id() {
+        // `Storage::insert` never reaches the raw, unchecked `insert` path
+        // for an id it already knows about (it swaps through `get_mut`
+        // instead); `serde_impl::deserialize` does call it directly on a
+        // sequence of ids it doesn't otherwise dedupe, so a repeated id
+        // must still overwrite in place rather than stranding the old slot.
+        use super::UnprotectedStorage;
+
+        let mut storage = DenseVecStorage::<Cdense>::new();
+        unsafe {
+            storage.insert(3, Cdense(1)).unwrap();
+            storage.insert(3, Cdense(2)).unwrap();
+            assert_eq!(*storage.get(3), Cdense(2));
+        }
+    }
+
+    #[test] fn null_test_clear() { test_clear::<Cflag>(); }
+
+    #[test]
+    fn null_test_presence_is_per_entity() {
+        let mut s = Storage::new(Box::new(Allocator::new()), Box::new(MaskedStorage::<Cflag>::new()));
+
+        s.insert(Entity::new(0, Generation(1)), Cflag).unwrap();
+        s.insert(Entity::new(1, Generation(1)), Cflag).unwrap();
+
+        s.remove(Entity::new(0, Generation(1))).unwrap();
+
+        assert!(s.get(Entity::new(0, Generation(1))).is_none());
+        assert!(s.get(Entity::new(1, Generation(1))).is_some());
+    }
+
+    #[test]
+    #[should_panic]
+    fn null_test_non_zero_sized_rejected() {
+        use super::UnprotectedStorage;
+
+        #[derive(Default)]
+        struct NotZst(u32);
+
+        let _ = <NullStorage<NotZst> as UnprotectedStorage<NotZst>>::new();
+    }
+}
+
+
+#[cfg(test)]
+mod local_test {
+    use std::rc::Rc;
+    use super::{LocalStorage, LocalWorld, VecStorage};
+    use world::Allocator;
+    use {Component, Entity, Generation};
+
+    struct Handle(Rc<u32>);
+    impl Component for Handle {
+        type Storage = VecStorage<Handle>;
+    }
+
+    #[test]
+    fn read_write() {
+        let alloc = Allocator::new();
+        let local = LocalStorage::<Handle>::new();
+
+        local.write(&alloc).insert(Entity::new(0, Generation(1)), Handle(Rc::new(42))).unwrap();
+
+        assert_eq!(*local.read(&alloc).get(Entity::new(0, Generation(1))).unwrap().0, 42);
+    }
+
+    #[test]
+    fn register_then_fetch() {
+        let alloc = Allocator::new();
+        let world = LocalWorld::new();
+        world.register::<Handle>();
+
+        world.storage::<Handle>().write(&alloc)
+            .insert(Entity::new(0, Generation(1)), Handle(Rc::new(42))).unwrap();
+
+        // Fetching again hands back a clone of the same underlying
+        // storage, not a fresh one.
+        assert_eq!(*world.storage::<Handle>().read(&alloc).get(Entity::new(0, Generation(1))).unwrap().0, 42);
+    }
+
+    #[test]
+    #[should_panic]
+    fn fetch_before_register_panics() {
+        let world = LocalWorld::new();
+        world.storage::<Handle>();
+    }
 }
 